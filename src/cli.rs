@@ -1,15 +1,37 @@
 use crate::coordinator;
 use crate::glue;
-use crate::store::Store;
+use crate::jobserver::JobServer;
+use crate::process::OutputMode;
+use crate::store::{RemoteCache, RemoteCacheMode, Store};
 use anyhow::{Context, Result};
 use clap::Parser;
 use core::mem::MaybeUninit;
 use std::path::PathBuf;
+use std::time::Duration;
 use tokio::runtime;
 
+/// `rbt gc` evicts cached outputs that are unlikely to be reused, so the
+/// store doesn't grow without bound as `Job::id`s accumulate across builds.
+#[derive(Debug, clap::Subcommand)]
+pub enum Subcommand {
+    Gc {
+        /// Evict anything not accessed within this many days.
+        #[clap(long, default_value = "90")]
+        max_age_days: u64,
+
+        /// If set, also evict least-recently-used entries (even if within
+        /// `max_age_days`) until the store is under this many bytes.
+        #[clap(long)]
+        max_bytes: Option<u64>,
+    },
+}
+
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct Cli {
+    #[clap(subcommand)]
+    command: Option<Subcommand>,
+
     #[clap(long, default_value = ".rbt")]
     root_dir: PathBuf,
 
@@ -21,21 +43,84 @@ pub struct Cli {
     /// number of CPU cores on the system.
     #[clap(long)]
     worker_threads: Option<usize>,
+
+    /// Base URL of a shared build cache to check before running a job and
+    /// populate after. Requests are unsigned GETs/PUTs, so this only works
+    /// against a public(-within-your-network) endpoint or one fronted by
+    /// something else that handles auth (e.g. a signed-URL proxy); pointing
+    /// it directly at a private S3 bucket will get you 403s.
+    #[clap(long)]
+    cache_url: Option<String>,
+
+    /// Whether `--cache-url` may be written to as well as read from. Local
+    /// developer machines will usually want `read-only` so a bad local build
+    /// can't poison the shared cache; CI fleets want `read-write` so they
+    /// populate it for everyone else.
+    #[clap(long, default_value = "read-only")]
+    cache_mode: RemoteCacheMode,
+
+    /// After the initial build, keep running and re-run only the jobs whose
+    /// inputs changed (plus their downstream dependents) whenever a watched
+    /// file is modified.
+    #[clap(long)]
+    watch: bool,
+
+    /// Kill and fail a job if it runs longer than this many seconds. If
+    /// unset, jobs may run indefinitely.
+    #[clap(long)]
+    job_timeout_secs: Option<u64>,
+
+    /// Stream each job's stdout/stderr to ours as it runs, instead of only
+    /// showing its last lines (as part of the error) if the job fails.
+    #[clap(long)]
+    stream_output: bool,
 }
 
 impl Cli {
     pub fn run(&self) -> Result<()> {
-        let rbt = Self::load();
-
         let db = self.open_db().context("could not open rbt's database")?;
 
-        let store = Store::new(
+        let mut store = Store::new(
             db.open_tree("store")
                 .context("could not open the store database")?,
             self.root_dir.join("store"),
         )
         .context("could not open store")?;
 
+        if let Some(Subcommand::Gc {
+            max_age_days,
+            max_bytes,
+        }) = &self.command
+        {
+            let report = store
+                .gc(Duration::from_secs(max_age_days * 24 * 60 * 60), *max_bytes)
+                .context("could not garbage-collect the store")?;
+
+            println!(
+                "rbt gc: evicted {} entr{} ({} bytes freed)",
+                report.evicted,
+                if report.evicted == 1 { "y" } else { "ies" },
+                report.bytes_freed,
+            );
+
+            return Ok(());
+        }
+
+        let rbt = Self::load();
+
+        if let Some(cache_url) = &self.cache_url {
+            store = store.with_remote(
+                RemoteCache::new(cache_url.clone(), self.cache_mode)
+                    .context("could not set up the remote cache")?,
+            );
+        }
+
+        let jobserver = match JobServer::from_env() {
+            Some(jobserver) => jobserver,
+            None => JobServer::new(self.worker_threads.unwrap_or_else(num_cpus::get))
+                .context("could not start a jobserver")?,
+        };
+
         let mut builder = coordinator::Builder::new(
             store,
             db.open_tree("file_hashes")
@@ -43,6 +128,13 @@ impl Cli {
             self.root_dir.join("workspaces"),
         );
         builder.add_root(&rbt.default);
+        builder.jobserver(jobserver);
+        builder.job_timeout(self.job_timeout_secs.map(Duration::from_secs));
+        builder.output_mode(if self.stream_output {
+            OutputMode::Stream
+        } else {
+            OutputMode::Buffer
+        });
 
         let mut coordinator = builder
             .build()
@@ -54,6 +146,12 @@ impl Cli {
             .block_on(coordinator.run_all())
             .context("failed to run jobs")?;
 
+        if self.watch {
+            return runtime
+                .block_on(crate::watch::run(&mut coordinator))
+                .context("failed while watching for changes");
+        }
+
         if self.print_root_output_paths {
             for root in coordinator.roots() {
                 println!(
@@ -1,19 +1,74 @@
 use crate::glue;
 use anyhow::Result;
 use itertools::Itertools;
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display};
-use std::hash::{Hash, Hasher};
 use std::path::{Component, PathBuf};
 use std::process::Command;
 
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
-pub struct Id(u64);
+pub struct Id([u8; 32]);
 
 impl Display for Id {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:x}", self.0)
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Hashes the parts of a job that determine its identity: the command to
+/// run, the content of each input file (not just its path), and the set of
+/// output paths it promises to produce. This is a plain SHA-256 over a
+/// canonical encoding of those pieces, so unlike `DefaultHasher` the result
+/// is stable across Rust versions, toolchains, and machines. That stability
+/// is what lets `Id` double as a cache key in `Store` between rbt upgrades.
+fn hash_job(
+    command: &glue::R3,
+    input_files: &HashSet<PathBuf>,
+    path_to_hash: &HashMap<PathBuf, String>,
+    outputs: &HashSet<PathBuf>,
+) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+
+    // Each section below is delimited with a `\0`-prefixed label followed by
+    // its item count, and each item within it with a `\0`, so that e.g.
+    // outputs of `{"a", "b"}` can never hash the same as `{"ab"}`, and the
+    // boundary between sections (say, the last input and the first output)
+    // can never be confused with a boundary inside one of them.
+    hasher.update(b"\0command\0");
+    hasher.update((command.args.len() as u64).to_le_bytes());
+    hasher.update(command.tool.f0.to_string().as_bytes());
+    for arg in &command.args {
+        hasher.update(b"\0");
+        hasher.update(arg.as_bytes());
+    }
+
+    hasher.update(b"\0inputs");
+    hasher.update((input_files.len() as u64).to_le_bytes());
+    for path in input_files.iter().sorted() {
+        match path_to_hash.get(path) {
+            Some(hash) => {
+                hasher.update(b"\0");
+                hasher.update(path.to_string_lossy().as_bytes());
+                hasher.update(b"\0");
+                hasher.update(hash.as_bytes());
+            }
+            None => anyhow::bail!("couldn't find a hash for `{}`", path.display()),
+        }
+    }
+
+    hasher.update(b"\0outputs");
+    hasher.update((outputs.len() as u64).to_le_bytes());
+    for output in outputs.iter().sorted() {
+        hasher.update(b"\0");
+        hasher.update(output.to_string_lossy().as_bytes());
     }
+
+    Ok(hasher.finalize().into())
 }
 
 #[derive(Debug)]
@@ -28,22 +83,15 @@ impl Job {
     pub fn from_glue(job: glue::Job, path_to_hash: &HashMap<PathBuf, String>) -> Result<Self> {
         let unwrapped = job.f0;
 
-        // TODO: is this the best hash for this kind of data? Should we find
-        // a faster one?
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-
         // TODO: when we can get commands from other jobs, we need to hash the
         // other tool and job instead of relying on the derived `Hash` trait
         // for this.
-        unwrapped.command.hash(&mut hasher);
-
         let mut input_files: HashSet<PathBuf> = HashSet::with_capacity(unwrapped.inputFiles.len());
         for path_str in unwrapped.inputFiles.iter().sorted() {
             let path = PathBuf::from(path_str.as_str());
 
-            match path_to_hash.get(&path) {
-                Some(hash) => hash.hash(&mut hasher),
-                None => anyhow::bail!("couldn't find a hash for `{}`", path.display()),
+            if !path_to_hash.contains_key(&path) {
+                anyhow::bail!("couldn't find a hash for `{}`", path.display());
             }
 
             input_files.insert(path);
@@ -78,12 +126,18 @@ impl Job {
                 };
             }
 
-            output.hash(&mut hasher);
             outputs.insert(output);
         }
 
+        let id = Id(hash_job(
+            &unwrapped.command.f0,
+            &input_files,
+            path_to_hash,
+            &outputs,
+        )?);
+
         Ok(Job {
-            id: Id(hasher.finish()),
+            id,
             command: unwrapped.command.f0,
             input_files,
             outputs,
@@ -103,6 +157,20 @@ impl From<&Job> for Command {
     }
 }
 
+impl Job {
+    /// Build the `Command` for this job the way [`From<&Job> for Command`]
+    /// does, but additionally hand it the jobserver's `MAKEFLAGS`
+    /// file-descriptor handshake so a sub-build this job spawns (e.g.
+    /// `make -jN`) draws from the same global token pool instead of its own.
+    /// Callers should hold a token acquired from `jobserver` for the
+    /// lifetime of the spawned process.
+    pub fn to_command(&self, jobserver: &crate::jobserver::JobServer) -> Command {
+        let mut command = Command::from(self);
+        jobserver.configure(&mut command);
+        command
+    }
+}
+
 impl Display for Job {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // intention: make a best-effort version of part of how the command
@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// Wraps the `jobserver` crate to share one global concurrency budget across
+/// rbt and any sub-builds its jobs spawn (e.g. a job that shells out to
+/// `make -jN`). Without this, `Cli::worker_threads` only bounds how many jobs
+/// *rbt* runs at once; a job that itself fans out further would oversubscribe
+/// the CPU regardless of that setting.
+#[derive(Debug, Clone)]
+pub struct JobServer {
+    client: jobserver::Client,
+}
+
+impl JobServer {
+    /// Start a new jobserver with `tokens` total concurrency available,
+    /// derived by the caller from `worker_threads`/the CPU count. Call this
+    /// when rbt is the top of the build (i.e. `from_env` found nothing to
+    /// inherit).
+    ///
+    /// Unlike cargo, which runs one rustc on the process's own implicit token
+    /// and only hands out `jobserver::Client::new`'s *extra* tokens to the
+    /// rest, every rbt job goes through `ProcessBuilder::exec` and acquires a
+    /// token from the pool, including the first one. So the implicit token
+    /// this process holds is never itself a work slot, and we ask
+    /// `Client::new` for the full `tokens` count rather than one fewer.
+    pub fn new(tokens: usize) -> Result<Self> {
+        let client = jobserver::Client::new(tokens).context("could not start a jobserver")?;
+
+        Ok(JobServer { client })
+    }
+
+    /// Honor a jobserver inherited from a parent build (e.g. rbt invoked
+    /// recursively from a `make` rule), so nested builds share one budget
+    /// instead of each opening their own.
+    pub fn from_env() -> Option<Self> {
+        unsafe { jobserver::Client::from_env() }.map(|client| JobServer { client })
+    }
+
+    /// Acquire a token, blocking until one is free. The returned guard
+    /// releases the token back to the pool on drop, mirroring how cargo's
+    /// jobserver usage ties token lifetime to the job that's using it.
+    pub fn acquire(&self) -> Result<jobserver::Acquired> {
+        self.client
+            .acquire()
+            .context("could not acquire a jobserver token")
+    }
+
+    /// Inject the `MAKEFLAGS` file-descriptor handshake into `command`'s
+    /// environment so a cooperating child (another `make`, or a recursive
+    /// `rbt`) can join this jobserver instead of opening its own.
+    pub fn configure(&self, command: &mut Command) {
+        self.client.configure(command);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::job::Job;
+use crate::jobserver::JobServer;
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+/// How many trailing lines of captured output to fold into a failed job's
+/// error message. Enough to usually show the actual compiler error without
+/// dumping an entire noisy build log.
+const ERROR_CONTEXT_LINES: usize = 20;
+
+/// Whether to forward a job's output to our own stdout/stderr as it's
+/// produced, or buffer it and only print it (as part of the error) if the
+/// job fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    Stream,
+    Buffer,
+}
+
+/// Runs a [`Job`]'s command the way `coordinator` needs it run: in the job's
+/// workspace, under the shared jobserver token budget, with an optional
+/// wall-clock timeout, producing an error with enough detail to act on when
+/// it fails instead of a bare exit code.
+pub struct ProcessBuilder<'a> {
+    job: &'a Job,
+    workspace: &'a Path,
+    jobserver: &'a JobServer,
+    timeout: Option<Duration>,
+    output_mode: OutputMode,
+    cancellation: Option<CancellationToken>,
+}
+
+impl<'a> ProcessBuilder<'a> {
+    pub fn new(job: &'a Job, workspace: &'a Path, jobserver: &'a JobServer) -> Self {
+        ProcessBuilder {
+            job,
+            workspace,
+            jobserver,
+            timeout: None,
+            output_mode: OutputMode::Buffer,
+            cancellation: None,
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+
+    /// Tie this job's lifetime to `token`: if it's cancelled before the job
+    /// finishes (e.g. `--watch` saw another file change), the job's whole
+    /// process group is killed on Unix rather than just its direct child, so
+    /// a job that itself forked a sub-build (e.g. `make -jN`) doesn't leave
+    /// orphans running after we restart.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub async fn exec(&self) -> Result<Output> {
+        // `acquire` blocks the calling thread until a token is free, so it
+        // has to run on a blocking-pool thread rather than directly in this
+        // async fn, or it could starve the runtime of worker threads that
+        // other jobs need in order to finish and release their own tokens.
+        let jobserver = self.jobserver.clone();
+        let _token = tokio::task::spawn_blocking(move || jobserver.acquire())
+            .await
+            .context("jobserver acquire task panicked")?
+            .with_context(|| format!("could not acquire a jobserver token for {}", self.job))?;
+
+        let mut command = Command::from(self.job.to_command(self.jobserver));
+        command.current_dir(self.workspace);
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+
+        // Put the child in its own process group so that, on cancellation,
+        // we can kill everything it spawned (e.g. a `make -jN` sub-build)
+        // instead of just the direct child and leaving its children running.
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("could not start {}", self.job))?;
+        let pid = child.id();
+
+        let stdout = child.stdout.take().expect("child stdout was piped");
+        let stderr = child.stderr.take().expect("child stderr was piped");
+        let streaming = self.output_mode == OutputMode::Stream;
+
+        let run = async {
+            let (stdout, stderr, status) = tokio::try_join!(
+                capture(stdout, streaming, || tokio::io::stdout()),
+                capture(stderr, streaming, || tokio::io::stderr()),
+                async {
+                    child
+                        .wait()
+                        .await
+                        .context("could not wait for child process")
+                },
+            )?;
+
+            Ok::<_, anyhow::Error>(Output {
+                status,
+                stdout,
+                stderr,
+            })
+        };
+
+        let timed = async {
+            match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, run).await,
+                None => Ok(run.await),
+            }
+        };
+
+        let timed_result = match &self.cancellation {
+            Some(token) => tokio::select! {
+                result = timed => result,
+                _ = token.cancelled() => {
+                    kill_process_group(pid);
+                    anyhow::bail!("{} was cancelled", self.job);
+                }
+            },
+            None => timed.await,
+        };
+
+        let output = match timed_result {
+            Ok(result) => result,
+            Err(_) => {
+                kill_process_group(pid);
+                anyhow::bail!(
+                    "{} did not finish within {:?} and was killed",
+                    self.job,
+                    self.timeout.expect("timeout elapsed without a timeout set"),
+                );
+            }
+        }
+        .with_context(|| format!("could not run {}", self.job))?;
+
+        if !output.status.success() {
+            anyhow::bail!(process_error(self.job, &output));
+        }
+
+        Ok(output)
+    }
+}
+
+/// Kill a job's whole process group rather than just its direct child, so a
+/// job that forked its own sub-build (e.g. `make -jN`) doesn't leave orphans
+/// running after a timeout or `--watch` cancellation. `pid` is `None` if the
+/// child had already been reaped; there's nothing to kill in that case.
+fn kill_process_group(pid: Option<u32>) {
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        // The child was put in its own process group (pgid == pid) at spawn
+        // time, so the negated pid addresses the whole group.
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Reads `pipe` to completion, optionally tee-ing each chunk to `also` (our
+/// own stdout/stderr) as it arrives, and always returns the full captured
+/// bytes so a failure can report the tail of the output even in streaming
+/// mode.
+async fn capture<R, W>(pipe: R, streaming: bool, also: impl FnOnce() -> W) -> Result<Vec<u8>>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(pipe);
+    let mut captured = Vec::new();
+    let mut also = streaming.then(also);
+
+    loop {
+        let buf = reader
+            .fill_buf()
+            .await
+            .context("could not read from child process")?;
+        if buf.is_empty() {
+            break;
+        }
+
+        let len = buf.len();
+        captured.extend_from_slice(buf);
+
+        if let Some(sink) = &mut also {
+            sink.write_all(buf).await.ok();
+        }
+
+        reader.consume(len);
+    }
+
+    Ok(captured)
+}
+
+/// Build a rich failure message: the shell-escaped command line, how the
+/// process ended (exit code or, on Unix, the signal that killed it), and the
+/// last `ERROR_CONTEXT_LINES` lines of whichever output stream has content.
+fn process_error(job: &Job, output: &Output) -> String {
+    let mut message = format!("{} failed", shell_escaped(job));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = output.status.signal() {
+            let _ = write!(message, " (killed by signal {})", signal);
+        } else if let Some(code) = output.status.code() {
+            let _ = write!(message, " (exit code {})", code);
+        }
+    }
+
+    #[cfg(not(unix))]
+    if let Some(code) = output.status.code() {
+        let _ = write!(message, " (exit code {})", code);
+    }
+
+    let tail = if !output.stderr.is_empty() {
+        &output.stderr
+    } else {
+        &output.stdout
+    };
+
+    let tail = String::from_utf8_lossy(tail);
+    let last_lines: Vec<&str> = tail.lines().rev().take(ERROR_CONTEXT_LINES).collect();
+
+    if !last_lines.is_empty() {
+        message.push_str("\n\n");
+        message.push_str(&last_lines.into_iter().rev().collect::<Vec<_>>().join("\n"));
+    }
+
+    message
+}
+
+fn shell_escaped(job: &Job) -> String {
+    let command = std::process::Command::from(job);
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut parts = vec![program];
+
+    parts.extend(
+        command
+            .get_args()
+            .map(|arg| shell_words::quote(&arg.to_string_lossy()).into_owned()),
+    );
+
+    parts.join(" ")
+}
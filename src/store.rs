@@ -0,0 +1,242 @@
+use crate::job::Id;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+mod remote;
+
+pub use remote::{RemoteCache, RemoteCacheMode};
+
+/// Bookkeeping we keep per cached `Id`, used by `gc` to decide what's safe to
+/// evict. Updated every time the store serves a cache hit.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct AccessRecord {
+    last_accessed: SystemTime,
+    access_count: u64,
+}
+
+impl AccessRecord {
+    fn fresh() -> Self {
+        AccessRecord {
+            last_accessed: SystemTime::now(),
+            access_count: 1,
+        }
+    }
+
+    fn bump(mut self) -> Self {
+        self.last_accessed = SystemTime::now();
+        self.access_count += 1;
+        self
+    }
+}
+
+/// The outcome of a `gc` run, surfaced to the CLI so users can see what it
+/// actually did.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub evicted: usize,
+    pub bytes_freed: u64,
+}
+
+/// Where we keep the outputs of jobs we've already run, keyed by `Job::id`.
+/// The `tree` holds one `AccessRecord` per cached id; the actual output files
+/// live under `root` in a directory named after the id's hex `Display`.
+#[derive(Debug)]
+pub struct Store {
+    tree: sled::Tree,
+    root: PathBuf,
+    remote: Option<RemoteCache>,
+}
+
+impl Store {
+    pub fn new(tree: sled::Tree, root: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("could not create store directory at {}", root.display()))?;
+
+        Ok(Store {
+            tree,
+            root,
+            remote: None,
+        })
+    }
+
+    /// Attach a remote cache. Once set, `has` will fall back to the remote on
+    /// a local miss, and `insert` uploads a newly run job's outputs to it.
+    /// `path_for` always names the local copy and never consults the remote.
+    pub fn with_remote(mut self, remote: RemoteCache) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Does the store already have outputs for this job, locally or remotely?
+    /// A remote hit is downloaded and materialized into the local store tree
+    /// as a side effect, so future calls are a local hit. Either way, a hit
+    /// bumps the id's access record so `gc` knows not to evict it yet.
+    ///
+    /// Async because a remote miss/hit means an HTTP round trip and (on a
+    /// local hit) sled does its own blocking I/O; this is always called from
+    /// within the coordinator's tokio runtime, so none of that should run
+    /// directly on one of its worker threads.
+    pub async fn has(&self, id: Id) -> Result<bool> {
+        let tree = self.tree.clone();
+        let key = id.to_string();
+        let found = tokio::task::spawn_blocking(move || tree.contains_key(key))
+            .await
+            .context("store lookup task panicked")??;
+
+        if found {
+            self.touch(id).await?;
+            return Ok(true);
+        }
+
+        match &self.remote {
+            Some(remote) if remote.fetch_into(id, &self.path_for(id)).await? => {
+                self.record(id, AccessRecord::fresh())
+                    .await
+                    .context("could not record remote cache hit in the store database")?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Record that `id`'s outputs now live on disk at `path_for(id)`, and (if
+    /// we have a writable remote) upload them so other machines can reuse
+    /// this job's result without rerunning it.
+    pub async fn insert(&self, id: Id) -> Result<()> {
+        self.record(id, AccessRecord::fresh())
+            .await
+            .context("could not record job in the store database")?;
+
+        if let Some(remote) = &self.remote {
+            remote.populate(id, &self.path_for(id)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn touch(&self, id: Id) -> Result<()> {
+        let tree = self.tree.clone();
+        let key = id.to_string();
+        let existing = tokio::task::spawn_blocking(move || tree.get(key))
+            .await
+            .context("store lookup task panicked")??;
+
+        let record = match existing {
+            Some(bytes) => bincode::deserialize::<AccessRecord>(&bytes)
+                .context("could not read access record")?
+                .bump(),
+            None => AccessRecord::fresh(),
+        };
+
+        self.record(id, record).await
+    }
+
+    async fn record(&self, id: Id, record: AccessRecord) -> Result<()> {
+        let bytes = bincode::serialize(&record).context("could not encode access record")?;
+
+        let tree = self.tree.clone();
+        let key = id.to_string();
+        tokio::task::spawn_blocking(move || tree.insert(key, bytes))
+            .await
+            .context("store write task panicked")??;
+
+        Ok(())
+    }
+
+    pub fn path_for(&self, id: Id) -> PathBuf {
+        self.root.join(id.to_string())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Evict cached outputs that haven't been accessed within `max_age`, then
+    /// (if still over `max_bytes`) evict least-recently-used entries until
+    /// the store fits. Each eviction removes the on-disk output tree before
+    /// the sled record, so a failure partway through leaves an orphaned (but
+    /// still-recorded) entry for a future `gc` to retry rather than outputs
+    /// on disk with no record pointing at them.
+    pub fn gc(&self, max_age: Duration, max_bytes: Option<u64>) -> Result<GcReport> {
+        let mut entries = Vec::new();
+        for item in self.tree.iter() {
+            let (key, value) = item.context("could not read store database entry")?;
+            let id_str = String::from_utf8(key.to_vec()).context("corrupt store database key")?;
+            let record: AccessRecord =
+                bincode::deserialize(&value).context("could not read access record")?;
+            entries.push((id_str, record));
+        }
+
+        let now = SystemTime::now();
+        let mut report = GcReport::default();
+
+        entries.sort_by_key(|(_, record)| record.last_accessed);
+
+        let mut remaining_bytes = max_bytes.map(|_| self.total_bytes(&entries)).transpose()?;
+
+        for (id_str, record) in entries {
+            let stale = now
+                .duration_since(record.last_accessed)
+                .unwrap_or(Duration::ZERO)
+                >= max_age;
+
+            let over_budget = match (max_bytes, remaining_bytes) {
+                (Some(limit), Some(used)) => used > limit,
+                _ => false,
+            };
+
+            if !stale && !over_budget {
+                continue;
+            }
+
+            let path = self.root.join(&id_str);
+            let size = dir_size(&path).unwrap_or(0);
+
+            if path.exists() {
+                std::fs::remove_dir_all(&path)
+                    .with_context(|| format!("could not remove {}", path.display()))?;
+            }
+
+            self.tree
+                .remove(id_str.as_bytes())
+                .context("could not remove store database entry")?;
+
+            report.evicted += 1;
+            report.bytes_freed += size;
+
+            if let Some(used) = &mut remaining_bytes {
+                *used = used.saturating_sub(size);
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn total_bytes(&self, entries: &[(String, AccessRecord)]) -> Result<u64> {
+        Ok(entries
+            .iter()
+            .map(|(id_str, _)| dir_size(&self.root.join(id_str)).unwrap_or(0))
+            .sum())
+    }
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut total = 0;
+
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry.context("could not walk store output directory")?;
+        if entry.file_type().is_file() {
+            total += entry
+                .metadata()
+                .context("could not read file metadata")?
+                .len();
+        }
+    }
+
+    Ok(total)
+}
@@ -0,0 +1,132 @@
+use crate::job::Id;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Whether we're allowed to write to the remote cache, or only read from it.
+/// CI fleets typically want `ReadWrite` so they populate the cache for
+/// everyone else, while local dev machines often want `ReadOnly` so a bad
+/// local build can't poison a shared cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteCacheMode {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl FromStr for RemoteCacheMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "read-only" => Ok(RemoteCacheMode::ReadOnly),
+            "read-write" => Ok(RemoteCacheMode::ReadWrite),
+            other => anyhow::bail!(
+                "`{}` is not a valid cache mode. Expected `read-only` or `read-write`.",
+                other
+            ),
+        }
+    }
+}
+
+/// A shared build cache that `Store` consults on a local miss. Since `Id` is
+/// already a pure function of a job's command, inputs, and outputs, it's a
+/// sound key to cache against across machines: a plain HTTPS endpoint
+/// storing one object per `Id`.
+///
+/// This talks to the endpoint with unsigned GETs/PUTs, so it only works
+/// against a public(-within-your-network) bucket or one fronted by something
+/// else that handles auth (e.g. a signed-URL proxy). It does not perform AWS
+/// SigV4 signing, so pointing it directly at a private S3 bucket will get
+/// you 403s.
+#[derive(Debug)]
+pub struct RemoteCache {
+    endpoint: String,
+    mode: RemoteCacheMode,
+    client: reqwest::Client,
+}
+
+impl RemoteCache {
+    /// `endpoint` is the base URL of the cache (e.g. a plain HTTPS endpoint
+    /// in front of an S3-compatible bucket).
+    pub fn new(endpoint: String, mode: RemoteCacheMode) -> Result<Self> {
+        Ok(RemoteCache {
+            endpoint,
+            mode,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    fn url_for(&self, id: Id) -> String {
+        format!("{}/{}.tar", self.endpoint.trim_end_matches('/'), id)
+    }
+
+    /// On a cache hit, download and unpack `id`'s outputs into `dest`.
+    /// Returns `false` (without error) on a plain cache miss.
+    pub async fn fetch_into(&self, id: Id, dest: &Path) -> Result<bool> {
+        let response = self
+            .client
+            .get(self.url_for(id))
+            .send()
+            .await
+            .with_context(|| format!("could not reach remote cache for `{}`", id))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        let response = response
+            .error_for_status()
+            .with_context(|| format!("remote cache returned an error for `{}`", id))?;
+
+        let bytes = response
+            .bytes()
+            .await
+            .with_context(|| format!("could not read remote cache body for `{}`", id))?;
+
+        // Unpacking a tarball is disk I/O plus CPU-bound decompression, not
+        // something that should run directly on a tokio worker thread; move
+        // it to the blocking pool the way `ProcessBuilder::exec` already does
+        // for the jobserver's blocking `acquire`.
+        let dest = dest.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&dest)
+                .with_context(|| format!("could not create {}", dest.display()))?;
+
+            tar::Archive::new(&bytes[..])
+                .unpack(&dest)
+                .with_context(|| format!("could not unpack remote cache outputs for `{}`", id))
+        })
+        .await
+        .context("remote cache unpack task panicked")??;
+
+        Ok(true)
+    }
+
+    /// Upload the outputs already materialized at `src` so other machines can
+    /// reuse this job's result. A no-op under `ReadOnly`.
+    pub async fn populate(&self, id: Id, src: &Path) -> Result<()> {
+        if self.mode == RemoteCacheMode::ReadOnly {
+            return Ok(());
+        }
+
+        let mut bytes = Vec::new();
+        {
+            let mut builder = tar::Builder::new(&mut bytes);
+            builder
+                .append_dir_all(".", src)
+                .with_context(|| format!("could not archive outputs for `{}`", id))?;
+            builder.finish().context("could not finish cache archive")?;
+        }
+
+        self.client
+            .put(self.url_for(id))
+            .body(bytes)
+            .send()
+            .await
+            .with_context(|| format!("could not upload `{}` to remote cache", id))?
+            .error_for_status()
+            .with_context(|| format!("remote cache rejected upload for `{}`", id))?;
+
+        Ok(())
+    }
+}
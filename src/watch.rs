@@ -0,0 +1,174 @@
+use crate::coordinator::Coordinator;
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// How long to wait after the last filesystem event before kicking off a
+/// rebuild. Editors and `git checkout` both tend to touch several files in a
+/// burst, and without this we'd restart the build (and kill whatever's
+/// already running) once per file instead of once per burst.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watch every input file reachable from `coordinator`'s roots and re-run
+/// `run_all` whenever one changes, reusing the store cache for everything
+/// whose content hash didn't change. Never returns under normal operation;
+/// it's meant to be the last thing `Cli::run` does under `--watch`.
+///
+/// We watch each input's *parent directory* rather than the file itself:
+/// editors and `git checkout` both save by writing a temp file and renaming
+/// it over the target, which unlinks the inode we'd otherwise have watched
+/// and leaves us deaf to every subsequent save. Watching the directory and
+/// filtering events down to paths we actually care about survives that
+/// (the same approach watchexec uses).
+pub async fn run(coordinator: &mut Coordinator) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Shared with the watcher's callback below so it can filter a
+    // directory's events down to just our inputs, and updated after each
+    // rebuild as the job graph (and so the input set) changes.
+    let paths = Arc::new(Mutex::new(watched_paths(coordinator)));
+
+    let filter = Arc::clone(&paths);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // Errors here mean the event itself couldn't be read, not that a
+        // rebuild failed; there's nothing more specific we can do with them
+        // than log and wait for the next event.
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => {
+                eprintln!("rbt: filesystem watcher error: {}", error);
+                return;
+            }
+        };
+
+        let is_relevant = {
+            let paths = filter.lock().expect("watch filter lock was poisoned");
+            event.paths.iter().any(|path| paths.contains(path))
+        };
+
+        if is_relevant {
+            let _ = tx.send(event);
+        }
+    })
+    .context("could not start the filesystem watcher")?;
+
+    let mut watched_dirs = parent_dirs(&paths.lock().expect("watch filter lock was poisoned"));
+    for dir in &watched_dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("could not watch {}", dir.display()))?;
+    }
+
+    // Set once a change arrives while a build is already running: it means
+    // we've cancelled that build and already have the event that should
+    // trigger the next one, so the next loop iteration shouldn't block on
+    // `rx.recv()` again before debouncing.
+    let mut have_pending_change = false;
+
+    loop {
+        wait_for_change(&mut rx, have_pending_change).await?;
+        have_pending_change = false;
+
+        println!("rbt: change detected, rebuilding...");
+
+        // `run_all_cancellable` recomputes each job's `Id` from the current
+        // file hashes, so a job whose inputs didn't change keeps its old id
+        // and is served from the store cache; only the changed job and
+        // whatever depends on it end up actually re-executing.
+        let token = CancellationToken::new();
+        let mut build = Box::pin(coordinator.run_all_cancellable(token.clone()));
+
+        loop {
+            tokio::select! {
+                result = &mut build => {
+                    if let Err(error) = result {
+                        eprintln!("rbt: build failed: {:#}", error);
+                    }
+                    break;
+                }
+                event = rx.recv() => {
+                    if event.is_none() {
+                        anyhow::bail!("filesystem watcher shut down unexpectedly");
+                    }
+
+                    // Another change arrived while this build was still
+                    // running: cancel it (which kills each still-running
+                    // job's process group) and wait for it to actually wind
+                    // down before relaunching, rather than letting two
+                    // overlapping builds race over the same workspaces.
+                    token.cancel();
+                    let _ = (&mut build).await;
+                    have_pending_change = true;
+                    break;
+                }
+            }
+        }
+
+        // Re-derive the watch list: a changed build graph (new input added
+        // to a job) may have added or removed paths we care about. Update
+        // the filter first so the callback never matches against a stale
+        // set, then reconcile which directories we actually watch.
+        let new_paths = watched_paths(coordinator);
+        *paths.lock().expect("watch filter lock was poisoned") = new_paths.clone();
+
+        let new_dirs = parent_dirs(&new_paths);
+
+        for dir in new_dirs.difference(&watched_dirs) {
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+        for dir in watched_dirs.difference(&new_dirs) {
+            let _ = watcher.unwatch(dir);
+        }
+
+        watched_dirs = new_dirs;
+    }
+}
+
+/// Block until a filesystem change is ready to act on: if `have_first` is
+/// `false`, wait for the first event; either way, then drain whatever else
+/// arrives within the debounce window so a burst of saves collapses into one
+/// rebuild instead of one per file.
+async fn wait_for_change(
+    rx: &mut mpsc::UnboundedReceiver<notify::Event>,
+    have_first: bool,
+) -> Result<()> {
+    if !have_first && rx.recv().await.is_none() {
+        anyhow::bail!("filesystem watcher shut down unexpectedly");
+    }
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(DEBOUNCE) => break,
+            more = rx.recv() => if more.is_none() {
+                anyhow::bail!("filesystem watcher shut down unexpectedly");
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn watched_paths(coordinator: &Coordinator) -> HashSet<PathBuf> {
+    coordinator
+        .jobs()
+        .flat_map(|job| job.input_files.iter().cloned())
+        .collect()
+}
+
+/// The set of directories that need a watch placed on them to see changes to
+/// every path in `paths`. A path with no parent (e.g. a bare relative file
+/// name) is watched via `.` instead.
+fn parent_dirs(paths: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+    paths
+        .iter()
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+        .collect()
+}